@@ -1,9 +1,10 @@
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use std::time::Duration;
 
-use failure::{bail, Error};
+use failure::{bail, format_err, Error};
 use rand;
 use tokio::net::UdpSocket;
+use tokio::time::timeout;
 use tokio_util::udp::UdpFramed;
 
 use futures::{Sink, Stream};
@@ -52,34 +53,46 @@ use crate::{Packet, PacketCodec, SrtSocket};
 #[derive(Debug, Clone)]
 #[must_use]
 pub struct SrtSocketBuilder {
-    local_addr: SocketAddr,
+    /// The local IP to bind to. `None` means "match whatever address family the remote
+    /// turns out to be", so IPv4 and IPv6 both work without the caller having to care.
+    /// A listener has no remote to match, so `None` binds it to IPv4.
+    local_ip: Option<IpAddr>,
+    local_port: u16,
     conn_type: ConnInitMethod,
     latency: Duration,
     crypto: Option<(u8, String)>,
 }
 
 /// Describes how this SRT entity will connect to the other.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnInitMethod {
     /// Listens on the local socket, expecting there to be a [`Connect`](ConnInitMethod::Connect) instance that eventually connects to this socket.
     /// This almost certianly menas you should use it with [`SrtSocketBuilder::local_port`],
     /// As otherwise there is no way to know which port it will bind to.
     Listen,
 
-    /// Connect to a listening socket. It expects the listen socket to be on the [`SocketAddr`] provided.
-    Connect(SocketAddr),
+    /// Connect to a listening socket, trying each [`SocketAddr`] in order (Happy
+    /// Eyeballs style) until one completes the handshake. This is how a hostname that
+    /// resolves to multiple addresses--like a dual-stack IPv4/IPv6 name--is handled.
+    Connect(Vec<SocketAddr>),
 
-    /// Connect to another [`Rendezvous`](ConnInitMethod::Rendezvous) connection. This is useful if both sides are behind a NAT. The [`SocketAddr`]
-    /// passed should be the **public** address and port of the other [`Rendezvous`](ConnInitMethod::Rendezvous) connection.
-    Rendezvous(SocketAddr),
+    /// Rendezvous with another [`Rendezvous`](ConnInitMethod::Rendezvous) connection, trying
+    /// each [`SocketAddr`] in order (Happy Eyeballs style) until one completes the handshake.
+    /// This is useful if both sides are behind a NAT. The addresses passed should be the
+    /// **public** address and port of the other [`Rendezvous`](ConnInitMethod::Rendezvous)
+    /// connection.
+    Rendezvous(Vec<SocketAddr>),
 }
 
 impl SrtSocketBuilder {
-    /// Defaults to binding to `0.0.0.0:0` (all adaptors, OS assigned port), 50ms latency, and no encryption.
+    /// Defaults to binding to all adaptors on an OS-assigned port (matching whichever
+    /// address family the remote turns out to be, or IPv4 for a listener), 50ms
+    /// latency, and no encryption.
     /// Generally easier to use [`new_listen`](SrtSocketBuilder::new_listen), [`new_connect`](SrtSocketBuilder::new_connect) or [`new_rendezvous`](SrtSocketBuilder::new_rendezvous)
     pub fn new(conn_type: ConnInitMethod) -> Self {
         SrtSocketBuilder {
-            local_addr: "0.0.0.0:0".parse().unwrap(),
+            local_ip: None,
+            local_port: 0,
             conn_type,
             latency: Duration::from_millis(50),
             crypto: None,
@@ -88,34 +101,48 @@ impl SrtSocketBuilder {
 
     pub fn new_listen() -> Self {
         SrtSocketBuilder {
-            local_addr: "0.0.0.0:0".parse().unwrap(),
+            local_ip: None,
+            local_port: 0,
             conn_type: ConnInitMethod::Listen,
             latency: Duration::from_millis(50),
             crypto: None,
         }
     }
 
-    /// Connects to the first address yielded by `to`
+    /// Connects to `to`, trying every address it resolves to (in order) until one
+    /// completes the handshake. Works the same whether `to` is a bare [`SocketAddr`], a
+    /// `"host:port"` string, or a dual-stack hostname that resolves to both IPv4 and
+    /// IPv6 addresses.
     ///
     /// # Panics
-    /// * `to` fails to resolve to a [`SocketAddr`]
+    /// * `to` fails to resolve to any [`SocketAddr`]
     pub fn new_connect(to: impl ToSocketAddrs) -> Self {
+        let addrs: Vec<SocketAddr> = to.to_socket_addrs().unwrap().collect();
+        assert!(!addrs.is_empty(), "`to` did not resolve to any addresses");
+
         SrtSocketBuilder {
-            local_addr: "0.0.0.0:0".parse().unwrap(),
-            conn_type: ConnInitMethod::Connect(to.to_socket_addrs().unwrap().next().unwrap()),
+            local_ip: None,
+            local_port: 0,
+            conn_type: ConnInitMethod::Connect(addrs),
             latency: Duration::from_millis(50),
             crypto: None,
         }
     }
 
-    /// Connects to the first address yielded by `to`
+    /// Rendezvous with `to`, which should be the other side's **public** address and
+    /// port, trying every address it resolves to (in order) until one completes the
+    /// handshake.
     ///
     /// # Panics
-    /// * `to` fails to resolve to a [`SocketAddr`]
+    /// * `to` fails to resolve to any [`SocketAddr`]
     pub fn new_rendezvous(to: impl ToSocketAddrs) -> Self {
+        let addrs: Vec<SocketAddr> = to.to_socket_addrs().unwrap().collect();
+        assert!(!addrs.is_empty(), "`to` did not resolve to any addresses");
+
         SrtSocketBuilder {
-            local_addr: "0.0.0.0:0".parse().unwrap(),
-            conn_type: ConnInitMethod::Connect(to.to_socket_addrs().unwrap().next().unwrap()),
+            local_ip: None,
+            local_port: 0,
+            conn_type: ConnInitMethod::Rendezvous(addrs),
             latency: Duration::from_millis(50),
             crypto: None,
         }
@@ -134,27 +161,33 @@ impl SrtSocketBuilder {
     }
 
     /// Sets the local address of the socket. This can be used to bind to just a specific network adapter instead of the default of all adapters.
+    /// Also pins the bound socket family: by default it is chosen to match whichever remote address is actually used, so IPv4 and IPv6 both work without this.
     pub fn local_addr(mut self, local_addr: IpAddr) -> Self {
-        self.local_addr.set_ip(local_addr);
+        self.local_ip = Some(local_addr);
 
         self
     }
 
     /// Sets the port to bind to. In general, to be used for [`ConnInitMethod::Listen`] and [`ConnInitMethod::Rendezvous`], but generally not [`ConnInitMethod::Connect`].
     pub fn local_port(mut self, port: u16) -> Self {
-        self.local_addr.set_port(port);
+        self.local_port = port;
 
         self
     }
 
     /// Set the latency of the connection. The more latency, the more time SRT has to recover lost packets.
+    ///
+    /// This also bounds how long [`connect`](SrtSocketBuilder::connect) will wait for any single address to complete its handshake before moving on to the next one.
     pub fn latency(mut self, latency: Duration) -> Self {
         self.latency = latency;
 
         self
     }
 
-    /// Se the crypto paramters. However, this is currently unimplemented.
+    /// Encrypts the connection with the given passphrase. `size` is the size, in bytes,
+    /// of the Stream Encrypting Key that will be negotiated during the handshake: 16,
+    /// 24, or 32. Both sides must use the same passphrase, but do not need to agree on
+    /// `size` ahead of time, as it's carried in the handshake.
     ///
     /// # Panics:
     /// * size is not 16, 24, or 32.
@@ -164,7 +197,27 @@ impl SrtSocketBuilder {
         self
     }
 
+    /// The local IP to use for a connection to `remote`: whatever was explicitly set
+    /// with [`local_addr`](SrtSocketBuilder::local_addr), or otherwise the unspecified
+    /// address of whichever family `remote` is, so the bound socket is always able to
+    /// reach it.
+    fn effective_local_ip(&self, remote: SocketAddr) -> IpAddr {
+        self.local_ip.unwrap_or(match remote {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        })
+    }
+
+    fn bind_addr(&self, ip: IpAddr) -> SocketAddr {
+        SocketAddr::new(ip, self.local_port)
+    }
+
     /// Connect with a custom socket. Not typically used, see [`connect`](SrtSocketBuilder::connect) instead.
+    ///
+    /// For [`ConnInitMethod::Connect`] and [`ConnInitMethod::Rendezvous`], only the first
+    /// address is used--`socket` is assumed to already be bound appropriately for it.
+    /// Prefer [`connect`](SrtSocketBuilder::connect) if you want the multi-address
+    /// fallback behavior.
     pub async fn connect_with_sock<T>(self, mut socket: T) -> Result<SrtSocket, Error>
     where
         T: Stream<Item = Result<(Packet, SocketAddr), Error>>
@@ -187,26 +240,39 @@ impl SrtSocketBuilder {
 
         let conn = match self.conn_type {
             ConnInitMethod::Listen => {
-                pending_connection::listen(&mut socket, rand::random(), self.latency).await?
+                pending_connection::listen(
+                    &mut socket,
+                    rand::random(),
+                    self.latency,
+                    self.crypto.clone(),
+                )
+                .await?
             }
-            ConnInitMethod::Connect(addr) => {
+            ConnInitMethod::Connect(addrs) => {
+                let addr = *addrs
+                    .first()
+                    .ok_or_else(|| format_err!("no addresses to connect to"))?;
                 pending_connection::connect(
                     &mut socket,
                     addr,
                     rand::random(),
-                    self.local_addr.ip(),
+                    self.effective_local_ip(addr),
                     self.latency,
                     self.crypto.clone(),
                 )
                 .await?
             }
-            ConnInitMethod::Rendezvous(remote_public) => {
+            ConnInitMethod::Rendezvous(addrs) => {
+                let addr = *addrs
+                    .first()
+                    .ok_or_else(|| format_err!("no addresses to rendezvous with"))?;
                 pending_connection::rendezvous(
                     &mut socket,
                     rand::random(),
-                    self.local_addr.ip(),
-                    remote_public,
+                    self.effective_local_ip(addr),
+                    addr,
                     self.latency,
+                    self.crypto.clone(),
                 )
                 .await?
             }
@@ -216,17 +282,87 @@ impl SrtSocketBuilder {
     }
 
     /// Connects to the remote socket. Resolves when it has been connected successfully.
+    ///
+    /// For [`ConnInitMethod::Connect`] and [`ConnInitMethod::Rendezvous`] with multiple
+    /// resolved addresses, each is tried in order (Happy Eyeballs style), giving each up
+    /// to [`latency`](SrtSocketBuilder::latency) to complete its handshake before moving
+    /// on to the next. If every address fails, the error returned aggregates all of
+    /// their failures, not just the last one.
     pub async fn connect(self) -> Result<SrtSocket, Error> {
-        let la = self.local_addr;
-        Ok(self
-            .connect_with_sock(UdpFramed::new(UdpSocket::bind(&la).await?, PacketCodec {}))
-            .await?)
+        match self.conn_type.clone() {
+            ConnInitMethod::Connect(addrs) => {
+                self.connect_first_of("connect to", addrs, |addr| ConnInitMethod::Connect(vec![addr]))
+                    .await
+            }
+            ConnInitMethod::Listen => {
+                let bind_addr = self.bind_addr(self.local_ip.unwrap_or(Ipv4Addr::UNSPECIFIED.into()));
+                self.connect_with_sock(UdpFramed::new(UdpSocket::bind(&bind_addr).await?, PacketCodec {}))
+                    .await
+            }
+            ConnInitMethod::Rendezvous(addrs) => {
+                self.connect_first_of("rendezvous with", addrs, |addr| {
+                    ConnInitMethod::Rendezvous(vec![addr])
+                })
+                .await
+            }
+        }
+    }
+
+    /// Tries each of `addrs` in order, binding a fresh socket of the matching family for
+    /// each attempt, until one completes the handshake within `self.latency` or they're
+    /// all exhausted. `verb` describes the attempt in the aggregated error message (e.g.
+    /// `"connect to"` or `"rendezvous with"`), and `singleton` builds the one-address
+    /// [`ConnInitMethod`] that attempt's [`connect_with_sock`](Self::connect_with_sock)
+    /// call should actually use--this is what makes each attempt target its own `addr`
+    /// rather than always redoing the handshake against `addrs[0]`.
+    async fn connect_first_of(
+        self,
+        verb: &str,
+        addrs: Vec<SocketAddr>,
+        singleton: impl Fn(SocketAddr) -> ConnInitMethod,
+    ) -> Result<SrtSocket, Error> {
+        if addrs.is_empty() {
+            bail!("no addresses to {}", verb);
+        }
+
+        let mut errs = Vec::with_capacity(addrs.len());
+
+        for addr in addrs {
+            let bind_addr = self.bind_addr(self.effective_local_ip(addr));
+
+            let attempt = async {
+                let socket =
+                    UdpFramed::new(UdpSocket::bind(&bind_addr).await?, PacketCodec {});
+                let mut attempt_builder = self.clone();
+                attempt_builder.conn_type = singleton(addr);
+                attempt_builder.connect_with_sock(socket).await
+            };
+
+            match timeout(self.latency, attempt).await {
+                Ok(Ok(sock)) => return Ok(sock),
+                Ok(Err(e)) => errs.push(format!("{}: {}", addr, e)),
+                Err(_) => errs.push(format!(
+                    "{}: timed out after {:?}",
+                    addr, self.latency
+                )),
+            }
+        }
+
+        Err(format_err!(
+            "failed to {} any of {} address(es): {}",
+            verb,
+            errs.len(),
+            errs.join("; ")
+        ))
     }
 
     /// Build a multiplexed connection. This acts as a sort of server, allowing many connections to this one socket.
     pub async fn build_multiplexed(self) -> Result<MultiplexServer, Error> {
         match self.conn_type {
-            ConnInitMethod::Listen => MultiplexServer::bind(&self.local_addr, self.latency).await,
+            ConnInitMethod::Listen => {
+                let bind_addr = self.bind_addr(self.local_ip.unwrap_or(Ipv4Addr::UNSPECIFIED.into()));
+                MultiplexServer::bind(&bind_addr, self.latency).await
+            }
             _ => bail!("Cannot bind multiplexed with any connection mode other than listen"),
         }
     }
@@ -0,0 +1,177 @@
+//! The established, post-handshake state of an SRT connection.
+
+use std::time::{Duration, Instant};
+
+use crate::crypto::{Crypto, KeyFlags};
+use crate::loss_list::LossList;
+use crate::packet::control::ControlPacket;
+use crate::packet::DataPacket;
+use crate::SeqNumber;
+
+/// The negotiated parameters of an established connection.
+#[derive(Clone)]
+pub struct ConnectionSettings {
+    pub init_seq_num: SeqNumber,
+    pub tsbpd_latency: Duration,
+
+    /// `Some` if the connection negotiated encryption during the handshake, holding the
+    /// Stream Encrypting Key both sides agreed on.
+    pub crypto: Option<Crypto>,
+}
+
+/// An established SRT connection: the negotiated [`ConnectionSettings`] plus the
+/// receive-side loss tracking needed to keep the data flowing.
+pub struct Connection {
+    settings: ConnectionSettings,
+    loss_list: LossList,
+    nak_period: Duration,
+}
+
+impl Connection {
+    /// `nak_period` is how often a still-missing range gets re-reported; in the absence
+    /// of a live RTT estimate yet, the handshake seeds it from the negotiated latency.
+    pub fn new(settings: ConnectionSettings, nak_period: Duration) -> Self {
+        Connection {
+            settings,
+            loss_list: LossList::new(),
+            nak_period,
+        }
+    }
+
+    pub fn settings(&self) -> &ConnectionSettings {
+        &self.settings
+    }
+
+    /// Encrypts `packet`'s payload in place and tags it with the live key's
+    /// [`KeyFlags`], if this connection negotiated encryption. Called by the socket
+    /// task just before a data packet goes out on the wire.
+    pub fn on_data_send(&self, packet: &mut DataPacket) {
+        if let Some(crypto) = &self.settings.crypto {
+            crypto.transform(packet.seq_number.as_raw(), &mut packet.payload);
+            packet.key_flags = Some(KeyFlags::Even);
+        }
+    }
+
+    /// Decrypts `packet`'s payload in place (if this connection negotiated encryption
+    /// and the packet is marked as encrypted via its KK field), and records its
+    /// sequence number as received so any gap before it is tracked for NAKs. Called by
+    /// the socket task just after a data packet comes off the wire.
+    pub fn on_data_received(&mut self, packet: &mut DataPacket, now: Instant) {
+        if let (Some(crypto), Some(_)) = (&self.settings.crypto, packet.key_flags) {
+            crypto.transform(packet.seq_number.as_raw(), &mut packet.payload);
+        }
+
+        self.loss_list.on_packet_received(packet.seq_number, now);
+    }
+
+    /// Pulls the next batch of NAKs due to be (re)sent, if any. The socket task should
+    /// call this on every timer tick and send the result as a `DataPacket`-sibling
+    /// [`ControlPacket::Nak`].
+    pub fn poll_nak(&mut self, now: Instant) -> Option<ControlPacket> {
+        self.loss_list
+            .poll_nak(now, self.nak_period)
+            .map(ControlPacket::Nak)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn seq(n: u32) -> SeqNumber {
+        SeqNumber::new_truncate(n)
+    }
+
+    fn connection(crypto: Option<Crypto>, nak_period: Duration) -> Connection {
+        Connection::new(
+            ConnectionSettings {
+                init_seq_num: seq(0),
+                tsbpd_latency: Duration::from_millis(100),
+                crypto,
+            },
+            nak_period,
+        )
+    }
+
+    #[test]
+    fn on_data_send_encrypts_and_on_data_received_decrypts() {
+        let sender_crypto = Crypto::new(16, "rendezvous test passphrase");
+        let km = sender_crypto.keying_material();
+        let receiver_crypto =
+            Crypto::from_keying_material(&km, "rendezvous test passphrase").unwrap();
+
+        let sender = connection(Some(sender_crypto), Duration::from_millis(100));
+        let mut receiver = connection(Some(receiver_crypto), Duration::from_millis(100));
+
+        let plaintext = b"hello from the data path".to_vec();
+        let mut packet = DataPacket {
+            seq_number: seq(1),
+            key_flags: None,
+            payload: plaintext.clone(),
+        };
+
+        sender.on_data_send(&mut packet);
+        assert_ne!(
+            packet.payload, plaintext,
+            "on_data_send should have encrypted the payload"
+        );
+        assert!(packet.key_flags.is_some());
+
+        receiver.on_data_received(&mut packet, Instant::now());
+        assert_eq!(
+            packet.payload, plaintext,
+            "on_data_received should have decrypted the payload back to the original"
+        );
+    }
+
+    #[test]
+    fn unencrypted_connection_leaves_payload_untouched() {
+        let sender = connection(None, Duration::from_millis(100));
+
+        let plaintext = b"no crypto negotiated".to_vec();
+        let mut packet = DataPacket {
+            seq_number: seq(1),
+            key_flags: None,
+            payload: plaintext.clone(),
+        };
+
+        sender.on_data_send(&mut packet);
+        assert_eq!(packet.payload, plaintext);
+        assert!(packet.key_flags.is_none());
+    }
+
+    #[test]
+    fn poll_nak_reports_a_gap_immediately_and_recovery_clears_it() {
+        let mut receiver = connection(None, Duration::from_millis(50));
+        let now = Instant::now();
+
+        let mut p1 = DataPacket {
+            seq_number: seq(1),
+            key_flags: None,
+            payload: Vec::new(),
+        };
+        receiver.on_data_received(&mut p1, now);
+
+        let mut p3 = DataPacket {
+            seq_number: seq(3),
+            key_flags: None,
+            payload: Vec::new(),
+        };
+        receiver.on_data_received(&mut p3, now);
+
+        // seq 2 is missing--due for an immediate NAK
+        assert!(matches!(receiver.poll_nak(now), Some(ControlPacket::Nak(_))));
+
+        // the retransmit of seq 2 arrives
+        let mut p2 = DataPacket {
+            seq_number: seq(2),
+            key_flags: None,
+            payload: Vec::new(),
+        };
+        receiver.on_data_received(&mut p2, now);
+
+        // nothing missing anymore, so no further NAK is ever due for it
+        let much_later = now + Duration::from_millis(500);
+        assert!(receiver.poll_nak(much_later).is_none());
+    }
+}
@@ -0,0 +1,353 @@
+//! SRT stream encryption: passphrase-based key exchange and per-packet AES-CTR
+//! encryption of `DataPacket` payloads.
+//!
+//! A [`Crypto`] holds the Stream Encrypting Key (SEK) and salt used to en/decrypt data,
+//! plus the Key Encrypting Key (KEK) derived from the connection's passphrase, which is
+//! used only to wrap/unwrap the SEK for transmission in a KMREQ/KMRSP control packet.
+
+use aes::cipher::generic_array::{typenum::U16, GenericArray};
+use aes::cipher::{BlockDecrypt, BlockEncrypt, NewBlockCipher};
+use aes::{Aes128, Aes192, Aes256};
+use failure::{bail, Error};
+use hmac::Hmac;
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Number of PBKDF2 iterations used to stretch the passphrase into a KEK, as mandated by
+/// the SRT specification (section 6.2.1 of haivision/srt's `srt-srtp` doc).
+const KEK_PBKDF2_ITERATIONS: u32 = 2048;
+
+/// Length, in bytes, of the salt used both for KEK derivation and for the per-packet CTR
+/// nonce.
+const SALT_LEN: usize = 16;
+
+/// Which of the two live keys (even/odd) a `DataPacket` was encrypted with, carried in
+/// the two-bit KK field of the data header so the SEK can be rotated without a glitch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFlags {
+    Even,
+    Odd,
+}
+
+impl KeyFlags {
+    /// The two-bit KK field value for this flag.
+    pub fn as_kk(self) -> u8 {
+        match self {
+            KeyFlags::Even => 0b01,
+            KeyFlags::Odd => 0b10,
+        }
+    }
+
+    /// Parses the KK field of a received data header.
+    pub fn from_kk(kk: u8) -> Option<KeyFlags> {
+        match kk & 0b11 {
+            0b01 => Some(KeyFlags::Even),
+            0b10 => Some(KeyFlags::Odd),
+            _ => None,
+        }
+    }
+}
+
+/// An AES-128/192/256 block cipher, picked at runtime based on the negotiated key size.
+#[derive(Clone)]
+enum AesKey {
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256),
+}
+
+impl AesKey {
+    fn new(key: &[u8]) -> AesKey {
+        match key.len() {
+            16 => AesKey::Aes128(Aes128::new(GenericArray::from_slice(key))),
+            24 => AesKey::Aes192(Aes192::new(GenericArray::from_slice(key))),
+            32 => AesKey::Aes256(Aes256::new(GenericArray::from_slice(key))),
+            _ => unreachable!("key size is validated in Crypto::new"),
+        }
+    }
+
+    fn encrypt_block(&self, block: &mut GenericArray<u8, U16>) {
+        match self {
+            AesKey::Aes128(c) => c.encrypt_block(block),
+            AesKey::Aes192(c) => c.encrypt_block(block),
+            AesKey::Aes256(c) => c.encrypt_block(block),
+        }
+    }
+
+    fn decrypt_block(&self, block: &mut GenericArray<u8, U16>) {
+        match self {
+            AesKey::Aes128(c) => c.decrypt_block(block),
+            AesKey::Aes192(c) => c.decrypt_block(block),
+            AesKey::Aes256(c) => c.decrypt_block(block),
+        }
+    }
+}
+
+/// Wraps `key` (the SEK, 16/24/32 bytes) with `kek` using AES Key Wrap (RFC 3394).
+/// Returns `key.len() + 8` bytes.
+fn aes_key_wrap(kek: &AesKey, key: &[u8]) -> Vec<u8> {
+    let n = key.len() / 8;
+    let mut r: Vec<[u8; 8]> = key.chunks(8).map(|c| c.try_into_array()).collect();
+    let mut a: [u8; 8] = [0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6];
+
+    for j in 0..=5u64 {
+        for i in 0..n {
+            let mut block = GenericArray::default();
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(&r[i]);
+            kek.encrypt_block(&mut block);
+
+            let t = j * (n as u64) + (i as u64) + 1;
+            a = block[..8].try_into_array();
+            for (b, tb) in a.iter_mut().zip(t.to_be_bytes().iter()) {
+                *b ^= tb;
+            }
+            r[i] = block[8..].try_into_array();
+        }
+    }
+
+    let mut out = Vec::with_capacity(key.len() + 8);
+    out.extend_from_slice(&a);
+    for block in r {
+        out.extend_from_slice(&block);
+    }
+    out
+}
+
+/// Reverses [`aes_key_wrap`], returning the unwrapped key or an error if the integrity
+/// check value doesn't match (wrong KEK, i.e. wrong passphrase).
+fn aes_key_unwrap(kek: &AesKey, wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+    if wrapped.len() % 8 != 0 || wrapped.len() < 16 {
+        bail!("wrapped key has invalid length {}", wrapped.len());
+    }
+    let n = wrapped.len() / 8 - 1;
+    let mut a: [u8; 8] = wrapped[..8].try_into_array();
+    let mut r: Vec<[u8; 8]> = wrapped[8..].chunks(8).map(|c| c.try_into_array()).collect();
+
+    for j in (0..=5u64).rev() {
+        for i in (0..n).rev() {
+            let t = j * (n as u64) + (i as u64) + 1;
+            for (b, tb) in a.iter_mut().zip(t.to_be_bytes().iter()) {
+                *b ^= tb;
+            }
+
+            let mut block = GenericArray::default();
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(&r[i]);
+            kek.decrypt_block(&mut block);
+
+            a = block[..8].try_into_array();
+            r[i] = block[8..].try_into_array();
+        }
+    }
+
+    if a != [0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6, 0xA6] {
+        bail!("key unwrap integrity check failed--wrong passphrase?");
+    }
+
+    Ok(r.into_iter().flatten().collect())
+}
+
+trait TryIntoArray {
+    fn try_into_array(&self) -> [u8; 8];
+}
+impl TryIntoArray for [u8] {
+    fn try_into_array(&self) -> [u8; 8] {
+        let mut out = [0u8; 8];
+        out.copy_from_slice(self);
+        out
+    }
+}
+
+/// Derives a Key Encrypting Key from `passphrase` and `salt` via PBKDF2-HMAC-SHA1.
+fn derive_kek(passphrase: &str, salt: &[u8], key_len: usize) -> Vec<u8> {
+    let mut kek = vec![0u8; key_len];
+    pbkdf2::pbkdf2::<Hmac<Sha1>>(
+        passphrase.as_bytes(),
+        salt,
+        KEK_PBKDF2_ITERATIONS,
+        &mut kek,
+    );
+    kek
+}
+
+/// Builds the AES-CTR counter block for sequence number `seq`, derived by XORing it into
+/// the low bits of the salt, as described in the SRT specification's keystream
+/// generation algorithm.
+fn counter_block(salt: &[u8; SALT_LEN], seq: u32) -> GenericArray<u8, U16> {
+    let mut block = GenericArray::clone_from_slice(salt);
+    for (i, b) in seq.to_be_bytes().iter().enumerate() {
+        block[12 + i] ^= b;
+    }
+    block
+}
+
+/// Encrypts or decrypts (the operations are identical in CTR mode) `data` in place,
+/// using `key` with the counter seeded from `salt` and the packet's sequence number.
+fn apply_ctr_keystream(key: &AesKey, salt: &[u8; SALT_LEN], seq: u32, data: &mut [u8]) {
+    for (block_idx, chunk) in data.chunks_mut(16).enumerate() {
+        let mut block = counter_block(salt, seq);
+        // Treat the block as a big-endian counter that increments once per 16 bytes of
+        // payload, so a packet longer than one AES block still gets a unique keystream
+        // per block.
+        let mut counter = u32::from_be_bytes([block[12], block[13], block[14], block[15]]);
+        counter = counter.wrapping_add(block_idx as u32);
+        block[12..].copy_from_slice(&counter.to_be_bytes());
+
+        key.encrypt_block(&mut block);
+
+        for (b, k) in chunk.iter_mut().zip(block.iter()) {
+            *b ^= k;
+        }
+    }
+}
+
+/// The Keying Material carried in a KMREQ (sent by the initiator) or KMRSP (echoed back
+/// by the responder once it has confirmed it can unwrap the key) control packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyingMaterial {
+    pub salt: [u8; SALT_LEN],
+    pub wrapped_sek: Vec<u8>,
+}
+
+/// Holds the negotiated encryption state for a single connection: the Stream Encrypting
+/// Key used to en/decrypt `DataPacket` payloads, and the salt/KEK used to wrap it for
+/// the handshake.
+#[derive(Clone)]
+pub struct Crypto {
+    sek: Vec<u8>,
+    salt: [u8; SALT_LEN],
+    kek: AesKey,
+}
+
+impl Crypto {
+    /// Generates a fresh random SEK of `key_size` bytes (16, 24, or 32) and derives the
+    /// KEK from `passphrase` over a freshly generated salt. This is called by the side
+    /// that originates the KMREQ.
+    ///
+    /// # Panics
+    /// * `key_size` is not 16, 24, or 32.
+    pub fn new(key_size: u8, passphrase: &str) -> Crypto {
+        assert!(
+            matches!(key_size, 16 | 24 | 32),
+            "invalid crypto key size: {}",
+            key_size
+        );
+
+        let mut rng = rand::thread_rng();
+
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+
+        let mut sek = vec![0u8; key_size as usize];
+        rng.fill_bytes(&mut sek);
+
+        let kek = AesKey::new(&derive_kek(passphrase, &salt, key_size as usize));
+
+        Crypto { sek, salt, kek }
+    }
+
+    /// Wraps this connection's SEK for transmission in a KMREQ/KMRSP control packet.
+    pub fn keying_material(&self) -> KeyingMaterial {
+        KeyingMaterial {
+            salt: self.salt,
+            wrapped_sek: aes_key_wrap(&self.kek, &self.sek),
+        }
+    }
+
+    /// Builds the responder/receive side's [`Crypto`] by unwrapping the SEK out of a
+    /// received [`KeyingMaterial`] using the shared `passphrase`.
+    pub fn from_keying_material(km: &KeyingMaterial, passphrase: &str) -> Result<Crypto, Error> {
+        let key_len = match km.wrapped_sek.len() {
+            24 => 16,
+            32 => 24,
+            40 => 32,
+            len => bail!("invalid wrapped key length: {}", len),
+        };
+
+        let kek = AesKey::new(&derive_kek(passphrase, &km.salt, key_len));
+        let sek = aes_key_unwrap(&kek, &km.wrapped_sek)?;
+
+        Ok(Crypto {
+            sek,
+            salt: km.salt,
+            kek,
+        })
+    }
+
+    /// Encrypts (or decrypts--CTR mode is an involution) `payload` in place, for the
+    /// `DataPacket` with sequence number `seq`. The KK field is not consulted here: this
+    /// type only tracks a single live SEK, so key rotation is a matter of constructing a
+    /// new `Crypto` and only accepting the `KeyFlags` that corresponds to it.
+    pub fn transform(&self, seq: u32, payload: &mut [u8]) {
+        apply_ctr_keystream(&self.kek_independent_key(), &self.salt, seq, payload);
+    }
+
+    fn kek_independent_key(&self) -> AesKey {
+        AesKey::new(&self.sek)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_wrap_round_trips() {
+        let kek = AesKey::new(&[0u8; 16]);
+        let key = [1u8; 16];
+
+        let wrapped = aes_key_wrap(&kek, &key);
+        assert_eq!(wrapped.len(), 24);
+
+        let unwrapped = aes_key_unwrap(&kek, &wrapped).unwrap();
+        assert_eq!(unwrapped, key);
+    }
+
+    #[test]
+    fn key_unwrap_detects_wrong_passphrase() {
+        let right_kek = AesKey::new(&derive_kek("correct horse", &[0u8; SALT_LEN], 16));
+        let wrong_kek = AesKey::new(&derive_kek("incorrect horse", &[0u8; SALT_LEN], 16));
+
+        let wrapped = aes_key_wrap(&right_kek, &[0xAB; 16]);
+        assert!(aes_key_unwrap(&wrong_kek, &wrapped).is_err());
+    }
+
+    #[test]
+    fn keying_material_round_trips_sek() {
+        let sender = Crypto::new(16, "hunter2");
+        let km = sender.keying_material();
+
+        let receiver = Crypto::from_keying_material(&km, "hunter2").unwrap();
+        assert_eq!(sender.sek, receiver.sek);
+    }
+
+    #[test]
+    fn wrong_passphrase_fails_to_reconstruct() {
+        let sender = Crypto::new(16, "hunter2");
+        let km = sender.keying_material();
+
+        assert!(Crypto::from_keying_material(&km, "wrong password").is_err());
+    }
+
+    #[test]
+    fn ctr_encrypt_decrypt_round_trips() {
+        let crypto = Crypto::new(16, "hunter2");
+
+        let mut data = b"hello, srt world".to_vec();
+        let original = data.clone();
+
+        crypto.transform(42, &mut data);
+        assert_ne!(data, original);
+
+        crypto.transform(42, &mut data);
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn key_flags_round_trip_kk_field() {
+        assert_eq!(KeyFlags::from_kk(KeyFlags::Even.as_kk()), Some(KeyFlags::Even));
+        assert_eq!(KeyFlags::from_kk(KeyFlags::Odd.as_kk()), Some(KeyFlags::Odd));
+        assert_eq!(KeyFlags::from_kk(0), None);
+    }
+}
@@ -1,4 +1,6 @@
 pub use connection::{Connection, ConnectionSettings};
+pub use crypto::{Crypto, KeyFlags, KeyingMaterial};
+pub use loss_list::LossList;
 pub use msg_number::MsgNumber;
 pub use packet::{ControlPacket, DataPacket, Packet, PacketParseError};
 pub use protocol::sender::congestion_control::LiveBandwidthMode;
@@ -9,11 +11,13 @@ pub use srt_version::SrtVersion;
 pub mod accesscontrol;
 pub mod connection;
 pub mod crypto;
+mod loss_list;
 mod modular_num;
 mod msg_number;
 pub mod packet;
 pub mod pending_connection;
 pub mod protocol;
 mod seq_number;
+pub mod simulation;
 mod socket_id;
 mod srt_version;
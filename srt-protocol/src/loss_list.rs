@@ -0,0 +1,289 @@
+//! Receive-side tracking of missing sequence numbers, and scheduling of the NAK control
+//! packets that ask the sender to retransmit them.
+//!
+//! [`LossList`] implements SRT's NAK-once-then-periodic policy: a newly detected gap is
+//! reported immediately, and any range still missing is re-reported on an RTT-derived
+//! timer until it either arrives or ages out past the TSBPD deadline.
+
+use std::time::{Duration, Instant};
+
+use crate::packet::control::loss_compression::compress_loss_list;
+use crate::SeqNumber;
+
+/// A single contiguous run of missing sequence numbers, `[start, end]` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LossRange {
+    start: SeqNumber,
+    end: SeqNumber,
+
+    /// When this range was first detected as missing--used to age it out past the
+    /// TSBPD deadline.
+    first_detected: Instant,
+
+    /// The last time a NAK was sent for (some part of) this range, if ever.
+    last_nak_sent: Option<Instant>,
+}
+
+impl LossRange {
+    fn contains(&self, seq: SeqNumber) -> bool {
+        self.start <= seq && seq <= self.end
+    }
+}
+
+/// Tracks which sequence numbers are currently missing on the receive side, and decides
+/// when to emit (or re-emit) a NAK for them.
+pub struct LossList {
+    ranges: Vec<LossRange>,
+    last_received: Option<SeqNumber>,
+}
+
+impl LossList {
+    pub fn new() -> Self {
+        LossList {
+            ranges: Vec::new(),
+            last_received: None,
+        }
+    }
+
+    /// Informs the loss list that `seq` was just received (in order or not). If it
+    /// leaves a gap after the highest sequence number received so far, that gap is
+    /// recorded as newly missing, due for an immediate NAK.
+    pub fn on_packet_received(&mut self, seq: SeqNumber, now: Instant) {
+        if let Some(last) = self.last_received {
+            if seq > last + 1 {
+                self.insert_missing(last + 1, seq - 1, now);
+            }
+        }
+
+        self.remove(seq);
+
+        if self.last_received.map_or(true, |last| seq > last) {
+            self.last_received = Some(seq);
+        }
+    }
+
+    /// Records `[start, end]` (inclusive) as newly missing.
+    fn insert_missing(&mut self, start: SeqNumber, end: SeqNumber, now: Instant) {
+        self.ranges.push(LossRange {
+            start,
+            end,
+            first_detected: now,
+            last_nak_sent: None,
+        });
+        self.merge_adjacent();
+    }
+
+    /// Removes `seq` from the loss list, as a (re)transmission of it has arrived.
+    /// Splits a range if `seq` was in its interior.
+    pub fn remove(&mut self, seq: SeqNumber) {
+        let mut new_ranges = Vec::with_capacity(self.ranges.len());
+
+        for range in self.ranges.drain(..) {
+            if !range.contains(seq) {
+                new_ranges.push(range);
+                continue;
+            }
+
+            if range.start < seq {
+                new_ranges.push(LossRange {
+                    end: seq - 1,
+                    ..range
+                });
+            }
+            if seq < range.end {
+                new_ranges.push(LossRange {
+                    start: seq + 1,
+                    ..range
+                });
+            }
+        }
+
+        self.ranges = new_ranges;
+    }
+
+    /// Drops any range that is entirely older than `deadline` relative to `now`--the
+    /// TSBPD deadline has passed, so the data is no longer useful even if retransmitted.
+    pub fn expire_older_than(&mut self, now: Instant, deadline: Duration) {
+        self.ranges
+            .retain(|r| now.duration_since(r.first_detected) < deadline);
+    }
+
+    fn merge_adjacent(&mut self) {
+        self.ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<LossRange> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end + 1 => {
+                    if range.end > last.end {
+                        last.end = range.end;
+                    }
+                    // if either side of the merge hasn't been NAK'd yet, the combined
+                    // range hasn't either--it still has an unreported sub-range that
+                    // needs to go out immediately, not wait out the older half's timer
+                    last.last_nak_sent = match (last.last_nak_sent, range.last_nak_sent) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        _ => None,
+                    };
+                }
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    /// Returns `true` if there is nothing currently missing.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Polls for the next batch of sequence numbers that should be NAK'd right now:
+    /// any range that has never had a NAK sent for it, or whose last NAK was more than
+    /// `nak_period` (derived from RTT) ago. Returns `None` if nothing is due.
+    ///
+    /// The caller is expected to call this periodically (e.g. on every RTT-derived
+    /// timer tick) and send a NAK control packet with the returned contents when
+    /// `Some`.
+    pub fn poll_nak(&mut self, now: Instant, nak_period: Duration) -> Option<Vec<u32>> {
+        let mut due: Vec<SeqNumber> = Vec::new();
+
+        for range in &mut self.ranges {
+            let is_due = match range.last_nak_sent {
+                None => true,
+                Some(last) => now.duration_since(last) >= nak_period,
+            };
+
+            if is_due {
+                let mut seq = range.start;
+                loop {
+                    due.push(seq);
+                    if seq == range.end {
+                        break;
+                    }
+                    seq = seq + 1;
+                }
+                range.last_nak_sent = Some(now);
+            }
+        }
+
+        if due.is_empty() {
+            return None;
+        }
+
+        due.sort();
+        Some(compress_loss_list(due.into_iter()).collect())
+    }
+}
+
+impl Default for LossList {
+    fn default() -> Self {
+        LossList::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn seq(n: u32) -> SeqNumber {
+        SeqNumber::new_truncate(n)
+    }
+
+    #[test]
+    fn detects_and_removes_gap() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        ll.on_packet_received(seq(1), now);
+        ll.on_packet_received(seq(5), now);
+        assert!(!ll.is_empty());
+
+        ll.on_packet_received(seq(2), now);
+        ll.on_packet_received(seq(3), now);
+        ll.on_packet_received(seq(4), now);
+        assert!(ll.is_empty());
+    }
+
+    #[test]
+    fn adjacent_ranges_merge() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        ll.insert_missing(seq(5), seq(6), now);
+        ll.insert_missing(seq(7), seq(9), now);
+
+        assert_eq!(ll.ranges.len(), 1);
+        assert_eq!(ll.ranges[0].start, seq(5));
+        assert_eq!(ll.ranges[0].end, seq(9));
+    }
+
+    #[test]
+    fn immediate_nak_on_first_poll() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        ll.on_packet_received(seq(1), now);
+        ll.on_packet_received(seq(5), now);
+
+        let nak = ll.poll_nak(now, Duration::from_millis(100)).unwrap();
+        assert_eq!(nak, vec![2 | (1 << 31), 4]);
+    }
+
+    #[test]
+    fn periodic_re_nak_until_arrival() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        ll.on_packet_received(seq(1), now);
+        ll.on_packet_received(seq(3), now);
+
+        // first poll reports it
+        assert!(ll.poll_nak(now, Duration::from_millis(100)).is_some());
+
+        // too soon--shouldn't re-report yet
+        let soon = now + Duration::from_millis(50);
+        assert!(ll.poll_nak(soon, Duration::from_millis(100)).is_none());
+
+        // past the period--should re-report
+        let later = now + Duration::from_millis(150);
+        assert_eq!(ll.poll_nak(later, Duration::from_millis(100)), Some(vec![2]));
+
+        // now it arrives, so no further NAKs for it
+        ll.on_packet_received(seq(2), later);
+        let much_later = later + Duration::from_millis(150);
+        assert!(ll.poll_nak(much_later, Duration::from_millis(100)).is_none());
+    }
+
+    #[test]
+    fn merge_with_already_reported_range_still_reports_new_part_immediately() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        // [5, 6] is detected and NAK'd right away...
+        ll.insert_missing(seq(5), seq(6), now);
+        assert!(ll.poll_nak(now, Duration::from_millis(100)).is_some());
+
+        // ...then [7, 9] is detected later and merges into it. The merged range has an
+        // unreported tail, so it must be due immediately rather than waiting out [5,
+        // 6]'s existing NAK timer.
+        let later = now + Duration::from_millis(10);
+        ll.insert_missing(seq(7), seq(9), later);
+
+        assert_eq!(ll.ranges.len(), 1);
+        let nak = ll.poll_nak(later, Duration::from_millis(100));
+        assert!(nak.is_some());
+    }
+
+    #[test]
+    fn expires_after_deadline() {
+        let mut ll = LossList::new();
+        let now = Instant::now();
+
+        ll.on_packet_received(seq(1), now);
+        ll.on_packet_received(seq(3), now);
+        assert!(!ll.is_empty());
+
+        ll.expire_older_than(now + Duration::from_secs(1), Duration::from_millis(500));
+        assert!(ll.is_empty());
+    }
+}
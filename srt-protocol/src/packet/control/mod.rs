@@ -0,0 +1,45 @@
+pub mod loss_compression;
+
+use crate::crypto::KeyingMaterial;
+use crate::SeqNumber;
+
+/// A control packet: anything that isn't payload data.
+#[derive(Debug, Clone)]
+pub enum ControlPacket {
+    /// Induction or conclusion handshake, carrying a cookie (the sender's own in an
+    /// induction, the peer's echoed back in a conclusion) and the sender's initial
+    /// sequence number.
+    Handshake(HandshakeControlInfo),
+
+    /// A compressed loss list, as produced by [`loss_compression::compress_loss_list`].
+    Nak(Vec<u32>),
+
+    /// KMREQ: the initiator's wrapped Stream Encrypting Key.
+    KeyManagementRequest(KeyingMaterial),
+
+    /// KMRSP: echoes the KMREQ's keying material back once the responder has confirmed
+    /// it can unwrap it with the shared passphrase.
+    KeyManagementResponse(KeyingMaterial),
+}
+
+/// Which step of a handshake a [`HandshakeControlInfo`] belongs to. The regular
+/// connect/listen handshake and the rendezvous handshake both go through this same
+/// induction-then-conclusion shape; tagging each packet with its phase means a
+/// retransmitted induction that crosses a conclusion on the wire can't be mistaken for
+/// it, or vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakePhase {
+    /// The first handshake packet: carries the sender's cookie so the peer can use it
+    /// to pick a rendezvous role, but isn't itself a commitment to connect.
+    Induction,
+
+    /// The handshake packet that actually establishes the connection.
+    Conclusion,
+}
+
+#[derive(Debug, Clone)]
+pub struct HandshakeControlInfo {
+    pub phase: HandshakePhase,
+    pub cookie: i32,
+    pub init_seq_num: SeqNumber,
+}
@@ -0,0 +1,34 @@
+//! The two kinds of packet SRT exchanges over the wire: control packets (handshake,
+//! NAK, keying material, ...) and data packets carrying payload.
+
+pub mod control;
+
+pub use control::ControlPacket;
+
+use failure::Fail;
+
+use crate::crypto::KeyFlags;
+use crate::SeqNumber;
+
+/// Either half of what gets sent over an SRT socket.
+#[derive(Debug, Clone)]
+pub enum Packet {
+    Control(ControlPacket),
+    Data(DataPacket),
+}
+
+#[derive(Debug, Fail)]
+pub enum PacketParseError {
+    #[fail(display = "packet too short: {} bytes", _0)]
+    TooShort(usize),
+}
+
+/// A single data packet: a sequence number and payload, plus--if the connection is
+/// encrypted--the two-bit KK field saying which of the even/odd keys the payload was
+/// encrypted with.
+#[derive(Debug, Clone)]
+pub struct DataPacket {
+    pub seq_number: SeqNumber,
+    pub key_flags: Option<KeyFlags>,
+    pub payload: Vec<u8>,
+}
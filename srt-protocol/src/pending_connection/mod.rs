@@ -0,0 +1,185 @@
+//! Establishes a [`Connection`] over a raw packet transport: the listen and connect
+//! handshakes, including the KMREQ/KMRSP key exchange when the builder asked for
+//! encryption. See [`rendezvous`] for the simultaneous-open handshake.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use failure::{bail, Error};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::crypto::Crypto;
+use crate::packet::control::{ControlPacket, HandshakeControlInfo, HandshakePhase};
+use crate::packet::Packet;
+use crate::{Connection, ConnectionSettings, SeqNumber};
+
+pub mod rendezvous;
+pub use rendezvous::rendezvous;
+
+/// Exchanges the KMREQ/KMRSP handshake over `socket` with `remote`: the initiator sends
+/// its wrapped SEK, the responder unwraps it with the shared passphrase and echoes the
+/// same keying material back to confirm it could. Returns `None` without sending
+/// anything if `crypto` is `None`--the connection is unencrypted.
+async fn exchange_keying_material<T>(
+    socket: &mut T,
+    remote: SocketAddr,
+    crypto: &Option<(u8, String)>,
+    we_initiate: bool,
+) -> Result<Option<Crypto>, Error>
+where
+    T: Stream<Item = Result<(Packet, SocketAddr), Error>>
+        + Sink<(Packet, SocketAddr), Error = Error>
+        + Unpin,
+{
+    let (size, passphrase) = match crypto {
+        None => return Ok(None),
+        Some((size, passphrase)) => (*size, passphrase.clone()),
+    };
+
+    if we_initiate {
+        let crypto = Crypto::new(size, &passphrase);
+        let km = crypto.keying_material();
+
+        socket
+            .send((
+                Packet::Control(ControlPacket::KeyManagementRequest(km.clone())),
+                remote,
+            ))
+            .await?;
+
+        loop {
+            match socket.next().await {
+                Some(Ok((
+                    Packet::Control(ControlPacket::KeyManagementResponse(rsp)),
+                    from,
+                ))) if from == remote && rsp == km => return Ok(Some(crypto)),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e),
+                None => bail!("connection closed while waiting for KMRSP"),
+            }
+        }
+    } else {
+        loop {
+            match socket.next().await {
+                Some(Ok((
+                    Packet::Control(ControlPacket::KeyManagementRequest(km)),
+                    from,
+                ))) if from == remote => {
+                    let crypto = Crypto::from_keying_material(&km, &passphrase)?;
+                    socket
+                        .send((
+                            Packet::Control(ControlPacket::KeyManagementResponse(km)),
+                            remote,
+                        ))
+                        .await?;
+                    return Ok(Some(crypto));
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e),
+                None => bail!("connection closed while waiting for KMREQ"),
+            }
+        }
+    }
+}
+
+/// Accepts a single incoming connection.
+pub async fn listen<T>(
+    socket: &mut T,
+    init_seq_num: SeqNumber,
+    latency: Duration,
+    crypto: Option<(u8, String)>,
+) -> Result<Connection, Error>
+where
+    T: Stream<Item = Result<(Packet, SocketAddr), Error>>
+        + Sink<(Packet, SocketAddr), Error = Error>
+        + Unpin,
+{
+    let (remote, remote_cookie) = loop {
+        match socket.next().await {
+            Some(Ok((Packet::Control(ControlPacket::Handshake(hs)), from)))
+                if hs.phase == HandshakePhase::Induction =>
+            {
+                break (from, hs.cookie)
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e),
+            None => bail!("connection closed while waiting for handshake"),
+        }
+    };
+
+    socket
+        .send((
+            Packet::Control(ControlPacket::Handshake(HandshakeControlInfo {
+                phase: HandshakePhase::Conclusion,
+                cookie: remote_cookie,
+                init_seq_num,
+            })),
+            remote,
+        ))
+        .await?;
+
+    // the listener, by convention, is the side that waits for the KMREQ
+    let crypto = exchange_keying_material(socket, remote, &crypto, false).await?;
+
+    Ok(Connection::new(
+        ConnectionSettings {
+            init_seq_num,
+            tsbpd_latency: latency,
+            crypto,
+        },
+        latency,
+    ))
+}
+
+/// Connects to a remote listening socket.
+pub async fn connect<T>(
+    socket: &mut T,
+    remote: SocketAddr,
+    init_seq_num: SeqNumber,
+    _local_ip: IpAddr,
+    latency: Duration,
+    crypto: Option<(u8, String)>,
+) -> Result<Connection, Error>
+where
+    T: Stream<Item = Result<(Packet, SocketAddr), Error>>
+        + Sink<(Packet, SocketAddr), Error = Error>
+        + Unpin,
+{
+    let our_cookie: i32 = rand::random();
+
+    socket
+        .send((
+            Packet::Control(ControlPacket::Handshake(HandshakeControlInfo {
+                phase: HandshakePhase::Induction,
+                cookie: our_cookie,
+                init_seq_num,
+            })),
+            remote,
+        ))
+        .await?;
+
+    loop {
+        match socket.next().await {
+            Some(Ok((Packet::Control(ControlPacket::Handshake(hs)), from)))
+                if from == remote && hs.phase == HandshakePhase::Conclusion =>
+            {
+                break
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e),
+            None => bail!("connection closed while waiting for handshake response"),
+        }
+    }
+
+    // the connector, by convention, initiates the key exchange
+    let crypto = exchange_keying_material(socket, remote, &crypto, true).await?;
+
+    Ok(Connection::new(
+        ConnectionSettings {
+            init_seq_num,
+            tsbpd_latency: latency,
+            crypto,
+        },
+        latency,
+    ))
+}
@@ -0,0 +1,265 @@
+//! Rendezvous cookie contest: SRT's rule for electing a single initiator when both
+//! peers start a rendezvous connection simultaneously, so two symmetric opens converge
+//! on one side driving the conclusion handshake instead of deadlocking.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use failure::{bail, Error};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+use super::exchange_keying_material;
+use crate::packet::control::{ControlPacket, HandshakeControlInfo, HandshakePhase};
+use crate::packet::Packet;
+use crate::{Connection, ConnectionSettings, SeqNumber};
+
+/// Which side drives the handshake to conclusion once both cookies are known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendezvousRole {
+    /// This peer's cookie won the contest: it sends the conclusion handshake.
+    Initiator,
+
+    /// The other peer's cookie won: this peer waits for their conclusion handshake and
+    /// responds to it.
+    Responder,
+}
+
+/// Elects a single initiator from the two peers' induction cookies, per the SRT
+/// rendezvous contest: the numerically greater cookie wins, so that two peers that
+/// raced to open a rendezvous connection at the same time agree on exactly one leader.
+/// On an exact tie (vanishingly unlikely with random 32-bit cookies, but possible),
+/// neither side can safely claim the role--the caller should regenerate its cookie and
+/// retry the induction exchange.
+pub fn elect_role(local_cookie: i32, remote_cookie: i32) -> Option<RendezvousRole> {
+    use std::cmp::Ordering;
+
+    match local_cookie.cmp(&remote_cookie) {
+        Ordering::Greater => Some(RendezvousRole::Initiator),
+        Ordering::Less => Some(RendezvousRole::Responder),
+        Ordering::Equal => None,
+    }
+}
+
+/// Drives retransmission of the induction handshake: a peer resends its induction
+/// packet on this schedule until it has seen the other side's, so induction packets
+/// crossing on the wire (or one being lost outright) don't stall the handshake.
+pub struct InductionRetransmitTimer {
+    period: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl InductionRetransmitTimer {
+    pub fn new(period: Duration) -> Self {
+        InductionRetransmitTimer {
+            period,
+            last_sent: None,
+        }
+    }
+
+    /// Returns `true` if the induction handshake is due to be (re)sent at `now`, and
+    /// records that it was sent.
+    pub fn should_send(&mut self, now: Instant) -> bool {
+        let due = match self.last_sent {
+            None => true,
+            Some(last) => now.duration_since(last) >= self.period,
+        };
+        if due {
+            self.last_sent = Some(now);
+        }
+        due
+    }
+}
+
+/// How often the induction handshake is retransmitted while racing the other side's.
+const INDUCTION_RETRANSMIT_PERIOD: Duration = Duration::from_millis(250);
+
+/// How often the `Initiator`'s conclusion handshake is retransmitted while waiting for
+/// the `Responder`'s ack of it--the same concern as induction retransmission: a single
+/// lost conclusion packet would otherwise hang the `Responder` forever.
+const CONCLUSION_RETRANSMIT_PERIOD: Duration = Duration::from_millis(250);
+
+/// Rendezvous with a peer that is simultaneously doing the same: races the induction
+/// handshakes (retransmitting on a timer until the other side's is seen, so either a
+/// lost packet or two inductions crossing on the wire don't stall things), elects a
+/// single initiator by cookie contest, and only moves to the data phase once both sides
+/// have agreed on--and acted on--that role.
+pub async fn rendezvous<T>(
+    socket: &mut T,
+    init_seq_num: SeqNumber,
+    _local_ip: IpAddr,
+    remote: SocketAddr,
+    latency: Duration,
+    crypto: Option<(u8, String)>,
+) -> Result<Connection, Error>
+where
+    T: Stream<Item = Result<(Packet, SocketAddr), Error>>
+        + Sink<(Packet, SocketAddr), Error = Error>
+        + Unpin,
+{
+    let our_cookie: i32 = rand::random();
+    let mut retransmit = InductionRetransmitTimer::new(INDUCTION_RETRANSMIT_PERIOD);
+
+    let remote_cookie = loop {
+        if retransmit.should_send(Instant::now()) {
+            socket
+                .send((
+                    Packet::Control(ControlPacket::Handshake(HandshakeControlInfo {
+                        phase: HandshakePhase::Induction,
+                        cookie: our_cookie,
+                        init_seq_num,
+                    })),
+                    remote,
+                ))
+                .await?;
+        }
+
+        match tokio::time::timeout(INDUCTION_RETRANSMIT_PERIOD, socket.next()).await {
+            Ok(Some(Ok((Packet::Control(ControlPacket::Handshake(hs)), from))))
+                if from == remote && hs.phase == HandshakePhase::Induction =>
+            {
+                break hs.cookie;
+            }
+            // not an induction handshake, or from someone else (e.g. our own
+            // conclusion reflected back, or a stray packet from a prior attempt)--keep
+            // waiting
+            Ok(Some(Ok(_))) => continue,
+            Ok(Some(Err(e))) => return Err(e),
+            Ok(None) => bail!("connection closed during rendezvous induction"),
+            // nothing arrived within the retransmit period--loop around, which
+            // retransmits our induction if it's due
+            Err(_) => continue,
+        }
+    };
+
+    let role = match elect_role(our_cookie, remote_cookie) {
+        Some(role) => role,
+        // an exact cookie tie: astronomically unlikely, but neither side can safely
+        // claim a role, so bail rather than risk both (or neither) driving conclusion
+        None => bail!("rendezvous cookie contest tied--please retry the connection"),
+    };
+
+    // whichever side won the contest drives the conclusion handshake; the other just
+    // waits for it, so both sides converge on the same parameters instead of each
+    // independently deciding to lead
+    let crypto = match role {
+        RendezvousRole::Initiator => {
+            // same concern as induction: a single lost conclusion packet shouldn't
+            // hang the `Responder` forever, so keep resending until it acks by
+            // sending its own conclusion back
+            let mut retransmit = InductionRetransmitTimer::new(CONCLUSION_RETRANSMIT_PERIOD);
+
+            loop {
+                if retransmit.should_send(Instant::now()) {
+                    socket
+                        .send((
+                            Packet::Control(ControlPacket::Handshake(HandshakeControlInfo {
+                                phase: HandshakePhase::Conclusion,
+                                cookie: remote_cookie,
+                                init_seq_num,
+                            })),
+                            remote,
+                        ))
+                        .await?;
+                }
+
+                match tokio::time::timeout(CONCLUSION_RETRANSMIT_PERIOD, socket.next()).await {
+                    Ok(Some(Ok((Packet::Control(ControlPacket::Handshake(hs)), from))))
+                        if from == remote && hs.phase == HandshakePhase::Conclusion =>
+                    {
+                        break;
+                    }
+                    // a stray induction retransmit crossing ours (the `Responder`
+                    // hasn't seen this conclusion yet), or anything else--keep waiting
+                    Ok(Some(Ok(_))) => continue,
+                    Ok(Some(Err(e))) => return Err(e),
+                    Ok(None) => {
+                        bail!("connection closed while waiting for rendezvous conclusion ack")
+                    }
+                    // nothing arrived within the retransmit period--loop around, which
+                    // retransmits our conclusion if it's due
+                    Err(_) => continue,
+                }
+            }
+
+            exchange_keying_material(socket, remote, &crypto, true).await?
+        }
+        RendezvousRole::Responder => {
+            loop {
+                match socket.next().await {
+                    Some(Ok((Packet::Control(ControlPacket::Handshake(hs)), from)))
+                        if from == remote && hs.phase == HandshakePhase::Conclusion =>
+                    {
+                        break
+                    }
+                    // a stray induction retransmit (the `Initiator` hadn't yet settled
+                    // on its role when it sent this), or anything else--keep waiting,
+                    // it isn't the conclusion
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e),
+                    None => bail!("connection closed waiting for rendezvous conclusion"),
+                }
+            }
+
+            // ack the conclusion so the `Initiator`'s retransmit loop can stop
+            socket
+                .send((
+                    Packet::Control(ControlPacket::Handshake(HandshakeControlInfo {
+                        phase: HandshakePhase::Conclusion,
+                        cookie: remote_cookie,
+                        init_seq_num,
+                    })),
+                    remote,
+                ))
+                .await?;
+
+            exchange_keying_material(socket, remote, &crypto, false).await?
+        }
+    };
+
+    Ok(Connection::new(
+        ConnectionSettings {
+            init_seq_num,
+            tsbpd_latency: latency,
+            crypto,
+        },
+        latency,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn greater_cookie_initiates() {
+        assert_eq!(elect_role(5, 3), Some(RendezvousRole::Initiator));
+        assert_eq!(elect_role(3, 5), Some(RendezvousRole::Responder));
+    }
+
+    #[test]
+    fn tied_cookie_requires_retry() {
+        assert_eq!(elect_role(7, 7), None);
+    }
+
+    #[test]
+    fn both_sides_agree_on_a_single_initiator() {
+        // symmetry: whichever side is "local" vs "remote", exactly one of the two
+        // resulting roles is `Initiator`, so the peers never both drive (or both wait
+        // for) the conclusion handshake
+        let (a, b) = (42, 17);
+        let role_a = elect_role(a, b).unwrap();
+        let role_b = elect_role(b, a).unwrap();
+
+        assert_ne!(role_a, role_b);
+    }
+
+    #[test]
+    fn retransmit_timer_fires_once_then_waits_for_period() {
+        let mut timer = InductionRetransmitTimer::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+
+        assert!(timer.should_send(t0));
+        assert!(!timer.should_send(t0 + Duration::from_millis(50)));
+        assert!(timer.should_send(t0 + Duration::from_millis(150)));
+    }
+}
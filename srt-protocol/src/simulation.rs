@@ -0,0 +1,374 @@
+//! A deterministic, in-process impaired link, for exercising loss/reorder recovery
+//! without shelling out to an external tool like `srt-live-transmit`.
+//!
+//! [`LossyConn`] wraps any `Sink<(Packet, SocketAddr)> + Stream<Item = (Packet, SocketAddr)>`
+//! and reproduces the kind of damage a real network link can do--drops, reordering, and
+//! jitter--all driven by a seeded PRNG so a failing run can be replayed exactly.
+
+use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use failure::Error;
+use futures::{Sink, Stream};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::Packet;
+
+/// Independent-per-packet loss, optionally augmented with bursty Gilbert-Elliott loss.
+#[derive(Debug, Clone, Copy)]
+pub struct LossModel {
+    /// Probability, in `[0, 1]`, that any given packet is dropped independently of the
+    /// others.
+    pub independent_loss: f64,
+
+    /// If set, models a two-state (good/bad) Gilbert-Elliott channel: `enter_burst` is
+    /// the probability of transitioning from the good state into the bad (bursty-loss)
+    /// state on any given packet, `exit_burst` the probability of recovering out of it,
+    /// and `burst_loss` the drop probability while in the bad state.
+    pub gilbert_elliott: Option<GilbertElliott>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GilbertElliott {
+    pub enter_burst: f64,
+    pub exit_burst: f64,
+    pub burst_loss: f64,
+}
+
+impl Default for LossModel {
+    fn default() -> Self {
+        LossModel {
+            independent_loss: 0.0,
+            gilbert_elliott: None,
+        }
+    }
+}
+
+/// Reordering model: with probability `probability`, a packet is held back. Once `window`
+/// packets are being held, they are all released together in reverse order. Packets not
+/// picked for holding pass straight through meanwhile, so there is no bound on how many
+/// of them overtake a held packet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReorderModel {
+    pub probability: f64,
+    pub window: usize,
+}
+
+/// Added one-way latency: a fixed `base` plus up to `jitter` of uniformly distributed
+/// additional delay.
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyModel {
+    pub base: Duration,
+    pub jitter: Duration,
+}
+
+impl Default for LatencyModel {
+    fn default() -> Self {
+        LatencyModel {
+            base: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+        }
+    }
+}
+
+/// Configuration for a [`LossyConn`]; build with `..Default::default()` to only override
+/// the fields a test cares about.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConfig {
+    pub seed: u64,
+    pub loss: LossModel,
+    pub reorder: ReorderModel,
+    pub latency: LatencyModel,
+}
+
+enum GeState {
+    Good,
+    Bad,
+}
+
+struct Scheduled<T> {
+    at: Instant,
+    // a monotonic tiebreaker so that packets delayed the same amount still leave in the
+    // order they entered the heap, except where reordering intentionally shuffled them
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Scheduled<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at && self.seq == other.seq
+    }
+}
+impl<T> Eq for Scheduled<T> {}
+impl<T> PartialOrd for Scheduled<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T> Ord for Scheduled<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so the `BinaryHeap` (a max-heap) acts as a min-heap by `at`
+        other.at.cmp(&self.at).then(other.seq.cmp(&self.seq))
+    }
+}
+
+/// Wraps an inner `Sink + Stream` of `(Packet, SocketAddr)`, dropping, reordering, and
+/// delaying packets according to a seeded, deterministic simulation. Intended to be
+/// passed directly to `SrtSocketBuilder::connect_with_sock` in tests, in
+/// place of a real `UdpFramed` socket.
+pub struct LossyConn<T> {
+    inner: T,
+    rng: StdRng,
+    config: LinkConfig,
+    ge_state: GeState,
+    next_seq: u64,
+    held_for_reorder: Vec<(Packet, SocketAddr)>,
+    in_flight: BinaryHeap<Scheduled<(Packet, SocketAddr)>>,
+}
+
+impl<T> LossyConn<T> {
+    pub fn new(inner: T, config: LinkConfig) -> Self {
+        LossyConn {
+            inner,
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            ge_state: GeState::Good,
+            next_seq: 0,
+            held_for_reorder: Vec::new(),
+            in_flight: BinaryHeap::new(),
+        }
+    }
+
+    /// Decides whether the next packet handed to [`Self::admit`] is lost, updating
+    /// Gilbert-Elliott state as a side effect.
+    fn should_drop(&mut self) -> bool {
+        if let Some(ge) = self.config.gilbert_elliott {
+            match self.ge_state {
+                GeState::Good => {
+                    if self.rng.gen_bool(ge.enter_burst) {
+                        self.ge_state = GeState::Bad;
+                    }
+                }
+                GeState::Bad => {
+                    if self.rng.gen_bool(ge.exit_burst) {
+                        self.ge_state = GeState::Good;
+                    }
+                }
+            }
+
+            let burst_drop = matches!(self.ge_state, GeState::Bad) && self.rng.gen_bool(ge.burst_loss);
+            if burst_drop {
+                return true;
+            }
+        }
+
+        self.rng.gen_bool(self.config.loss.independent_loss)
+    }
+
+    fn delay(&mut self) -> Duration {
+        if self.config.latency.jitter.is_zero() {
+            return self.config.latency.base;
+        }
+        let jitter_ms = self.rng.gen_range(0..=self.config.latency.jitter.as_millis() as u64);
+        self.config.latency.base + Duration::from_millis(jitter_ms)
+    }
+
+    /// Runs one packet through the loss/reorder/latency pipeline, scheduling it for
+    /// eventual delivery (or dropping it outright).
+    fn admit(&mut self, packet: (Packet, SocketAddr)) {
+        if self.should_drop() {
+            return;
+        }
+
+        if self.config.reorder.probability > 0.0 && self.rng.gen_bool(self.config.reorder.probability) {
+            self.held_for_reorder.push(packet);
+            if self.held_for_reorder.len() < self.config.reorder.window {
+                return;
+            }
+            // release the held packets out of order: reverse so the most
+            // recently-added (this one) actually goes out first, rather than being
+            // scheduled in the same order it arrived
+            let mut held = std::mem::take(&mut self.held_for_reorder);
+            held.reverse();
+            for p in held {
+                self.schedule(p);
+            }
+            return;
+        }
+
+        self.schedule(packet);
+    }
+
+    fn schedule(&mut self, packet: (Packet, SocketAddr)) {
+        let delay = self.delay();
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.in_flight.push(Scheduled {
+            at: Instant::now() + delay,
+            seq,
+            item: packet,
+        });
+    }
+}
+
+impl<T> Stream for LossyConn<T>
+where
+    T: Stream<Item = Result<(Packet, SocketAddr), Error>> + Unpin,
+{
+    type Item = Result<(Packet, SocketAddr), Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        while let Poll::Ready(next) = Pin::new(&mut this.inner).poll_next(cx) {
+            match next {
+                Some(Ok(packet)) => this.admit(packet),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => break,
+            }
+        }
+
+        match this.in_flight.peek() {
+            Some(scheduled) if scheduled.at <= Instant::now() => {
+                let scheduled = this.in_flight.pop().unwrap();
+                Poll::Ready(Some(Ok(scheduled.item)))
+            }
+            Some(_) => {
+                // not yet due--ask to be polled again shortly rather than never waking
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Sink<(Packet, SocketAddr)> for LossyConn<T>
+where
+    T: Sink<(Packet, SocketAddr), Error = Error> + Unpin,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: (Packet, SocketAddr)) -> Result<(), Self::Error> {
+        Pin::new(&mut self.get_mut().inner).start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DataPacket, SeqNumber};
+
+    fn packet(n: u32) -> (Packet, SocketAddr) {
+        (
+            Packet::Data(DataPacket {
+                seq_number: SeqNumber::new_truncate(n),
+                key_flags: None,
+                payload: Vec::new(),
+            }),
+            "127.0.0.1:0".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn same_seed_drops_same_packets() {
+        let config = LinkConfig {
+            seed: 42,
+            loss: LossModel {
+                independent_loss: 0.3,
+                gilbert_elliott: None,
+            },
+            ..Default::default()
+        };
+
+        let mut a = LossyConn::new((), config.clone());
+        let mut b = LossyConn::new((), config);
+
+        let decisions_a: Vec<bool> = (0..100).map(|_| a.should_drop()).collect();
+        let decisions_b: Vec<bool> = (0..100).map(|_| b.should_drop()).collect();
+
+        assert_eq!(decisions_a, decisions_b);
+    }
+
+    #[test]
+    fn different_seeds_usually_diverge() {
+        let mut a = LossyConn::new(
+            (),
+            LinkConfig {
+                seed: 1,
+                loss: LossModel {
+                    independent_loss: 0.5,
+                    gilbert_elliott: None,
+                },
+                ..Default::default()
+            },
+        );
+        let mut b = LossyConn::new(
+            (),
+            LinkConfig {
+                seed: 2,
+                loss: LossModel {
+                    independent_loss: 0.5,
+                    gilbert_elliott: None,
+                },
+                ..Default::default()
+            },
+        );
+
+        let decisions_a: Vec<bool> = (0..200).map(|_| a.should_drop()).collect();
+        let decisions_b: Vec<bool> = (0..200).map(|_| b.should_drop()).collect();
+
+        assert_ne!(decisions_a, decisions_b);
+    }
+
+    #[test]
+    fn reorder_actually_changes_delivery_order() {
+        let config = LinkConfig {
+            seed: 7,
+            reorder: ReorderModel {
+                probability: 1.0,
+                window: 4,
+            },
+            ..Default::default()
+        };
+        let mut conn = LossyConn::new((), config);
+
+        let input: Vec<u32> = (0..4).collect();
+        for n in &input {
+            conn.admit(packet(*n));
+        }
+
+        // the whole window was held and then released in one go--nothing should be
+        // left waiting in `held_for_reorder`, and everything should have been
+        // scheduled for delivery
+        assert!(conn.held_for_reorder.is_empty());
+        assert_eq!(conn.in_flight.len(), input.len());
+
+        let mut delivered = Vec::new();
+        while let Some(scheduled) = conn.in_flight.pop() {
+            delivered.push(match scheduled.item.0 {
+                Packet::Data(d) => d.seq_number.as_raw(),
+                _ => unreachable!(),
+            });
+        }
+
+        assert_ne!(delivered, input, "reordering should change delivery order");
+    }
+}
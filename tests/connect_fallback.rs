@@ -0,0 +1,40 @@
+//! Proves `ConnInitMethod::Connect`'s Happy-Eyeballs fallback actually dials each
+//! address with its own handshake, rather than always retrying `addrs[0]`.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use failure::Error;
+use futures::join;
+
+use srt::{ConnInitMethod, SrtSocketBuilder};
+
+#[tokio::test]
+async fn falls_back_to_the_second_address_when_the_first_is_unreachable() -> Result<(), Error> {
+    let _ = env_logger::try_init();
+
+    let listen_addr: SocketAddr = "127.0.0.1:8997".parse().unwrap();
+    // nothing is listening here--the first attempt must fail (or time out) and move on
+    let unreachable_addr: SocketAddr = "127.0.0.1:8998".parse().unwrap();
+
+    let listener = SrtSocketBuilder::new(ConnInitMethod::Listen)
+        .local_port(8997)
+        .latency(Duration::from_millis(200))
+        .connect();
+
+    let connector = SrtSocketBuilder::new(ConnInitMethod::Connect(vec![
+        unreachable_addr,
+        listen_addr,
+    ]))
+    .latency(Duration::from_millis(200))
+    .connect();
+
+    let (listener, connector) = join!(listener, connector);
+
+    // if the fallback were still broken, the connector would keep retrying
+    // `unreachable_addr` and never reach the listener, so both sides would fail here
+    listener?;
+    connector?;
+
+    Ok(())
+}
@@ -124,7 +124,7 @@ async fn stransmit_server() -> Result<(), Error> {
     // start SRT connector
     let serv = async {
         let mut sender =
-            SrtSocketBuilder::new(ConnInitMethod::Connect("127.0.0.1:2000".parse().unwrap()))
+            SrtSocketBuilder::new(ConnInitMethod::Connect(vec!["127.0.0.1:2000".parse().unwrap()]))
                 .latency(Duration::from_millis(99))
                 .connect_sender()
                 .await